@@ -5,13 +5,25 @@
 extern crate rustc_ast;
 extern crate rustc_errors;
 extern crate rustc_hir;
+extern crate rustc_middle;
+extern crate rustc_parse;
+extern crate rustc_session;
 extern crate rustc_span;
 
-use clippy_utils::{attrs::is_doc_hidden, diagnostics::span_lint_and_then, source::snippet_opt};
+use clippy_utils::{
+    attrs::is_doc_hidden, diagnostics::span_lint_and_then, macros::root_macro_call_first_node,
+    source::snippet_opt, ty::is_type_diagnostic_item,
+};
 use rustc_ast::AttrKind;
-use rustc_hir::{FnSig, Item, ItemKind};
+use rustc_errors::Applicability;
+use rustc_hir::{
+    intravisit::{self, Visitor},
+    BodyId, Expr, ExprKind, FnSig, HirId, ImplItem, ImplItemKind, Item, ItemKind, OwnerId, TraitFn,
+    TraitItem, TraitItemKind,
+};
 use rustc_lint::{LateContext, LateLintPass, LintContext};
-use rustc_span::{BytePos, SourceFileAndLine, Span};
+use rustc_middle::hir::nested_filter;
+use rustc_span::{sym, BytePos, SourceFileAndLine, Span};
 use serde::Deserialize;
 use std::{
     fmt::Write,
@@ -22,31 +34,40 @@ use std::{
 // https://docs.rs/async-openai/latest/async_openai/
 mod openai;
 
+mod markdown;
+
 const OPENAI_API_KEY: &str = "OPENAI_API_KEY";
 
-const URL: &str = "https://api.openai.com/v1/completions";
+const URL: &str = "https://api.openai.com/v1/chat/completions";
 
 const DEFAULT_PROMPT: &str = "An elaborate, high quality rustdoc comment for the above function:";
-const DEFAULT_MODEL: &str = "code-davinci-002";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
 const DEFAULT_MAX_TOKENS: u32 = 1000;
 const DEFAULT_TEMPERATURE: f32 = 0.2;
 
 const MOCK_COMPLETION: &str = "/// A doc comment generated by OpenAI.\n";
 
+const SYSTEM_PREAMBLE: &str = "You write Rust doc comments. Respond with only the doc comment, as \
+                                a sequence of lines each beginning with `///`. Do not include a \
+                                code fence or repeat the item's code.";
+
 const STOP: &str = "\n```";
 
 dylint_linting::impl_late_lint! {
     /// ⚠️ DO NOT RUN THIS LINT ON PRIVATE SOURCE CODE ⚠️
     ///
     /// ### What it does
-    /// Checks for functions missing [doc comments].
+    /// Checks for exported items missing [doc comments]: functions, structs, enums, unions,
+    /// traits, type aliases, constants, statics, and trait/impl associated items.
     ///
     /// ### Why is this bad?
-    /// Understanding what a function does is easier given a description of the function rather than
+    /// Understanding what an item does is easier given a description of the item rather than
     /// just its code.
     ///
     /// ### Known problems
-    /// The lint is currently enabled only for functions.
+    /// The lint does not check modules, enum variants, struct fields, or macros. Impl items that
+    /// implement a trait are not checked either, since their documentation belongs on the trait
+    /// definition.
     ///
     /// ### Example
     /// ```rust
@@ -60,18 +81,31 @@ dylint_linting::impl_late_lint! {
     ///
     /// ### OpenAI
     /// If the environment variable `OPENAI_API_KEY` is set to an [OpenAI API key], the lint will
-    /// suggest a doc comment generated by OpenAI. The prompt sent to OpenAI has the following form:
+    /// suggest a doc comment generated by OpenAI's [Chat Completions API]. The request's `messages`
+    /// have the following form:
     /// ````ignore
-    /// ```rust
-    /// <function declaration>
-    /// ```
-    /// An elaborate, high quality rustdoc comment for the above function:
-    /// ```rust
+    /// [system] <instructions to reply with only `///` lines> An elaborate, high quality rustdoc
+    ///          comment for the above function:
+    /// [user]   ```rust
+    ///          <item declaration>
+    ///          ```
     /// ````
-    /// The prompt's [`stop` parameter] is set to `["\n```"]`. Thus, OpenAI should stop generating tokens once the second code block is complete. The suggested doc comment is the one that appears in that code block, if any.
+    /// ("function" is replaced with "struct", "trait method", etc., depending on the kind of item
+    /// being documented.) The suggested doc comment is the run of `///` (or `//!`) lines at the
+    /// start of the model's reply, if any. Before it is suggested, the comment is parsed as
+    /// Markdown: a needless `fn main() {}` wrapper around a ```rust example is stripped, the
+    /// suggestion is discarded if the comment's code fences are unbalanced, and it is downgraded
+    /// if an example fails to parse as Rust.
     ///
     /// The phrase "An elaborate..." is configurable (see below).
     ///
+    /// If the item is a function (or method) that is `unsafe`, returns a `Result`, or can panic
+    /// (e.g., it calls `unwrap`, `expect`, indexes a slice, or invokes
+    /// `panic!`/`assert!`/`unreachable!` and friends), the prompt additionally asks OpenAI for a
+    /// `# Safety`, `# Errors`, or `# Panics` section, respectively. The generated comment is
+    /// checked for those sections afterward, and a warning is emitted if any required section is
+    /// still missing.
+    ///
     /// ### Configuration
     /// Certain [OpenAI parameters] can be configured by setting them in the
     /// `missing_doc_comment_openai` table of the linted workspace's [`dylint.toml` file]. Example:
@@ -83,7 +117,7 @@ dylint_linting::impl_late_lint! {
     /// The following parameters are supported:
     /// - `prompt` (default "An elaborate, high quality rustdoc comment for the above function:").
     ///   This default is based on OpenAI's [Write a Python docstring] example.
-    /// - `model` (default "[code-davinci-002]")
+    /// - `model` (default "[gpt-4o-mini]")
     /// - `temperature` (default 0.2). Note that this default is less than OpenAI's default (1.0).
     ///   Per the [`temperature` documentation], "Higher values like 0.8 will make the output more
     ///   random, while lower values like 0.2 will make it more focused and deterministic."
@@ -92,12 +126,12 @@ dylint_linting::impl_late_lint! {
     /// - `frequency_penalty` (default none, i.e., use OpenAI's default)
     ///
     /// [`dylint.toml` file]: https://github.com/trailofbits/dylint#configurable-libraries
-    /// [`stop` parameter]: https://platform.openai.com/docs/api-reference/completions/create#completions/create-stop
-    /// [`temperature` documentation]: https://platform.openai.com/docs/api-reference/completions/create#completions/create-temperature
-    /// [code-davinci-002]: https://platform.openai.com/docs/models/codex
+    /// [`temperature` documentation]: https://platform.openai.com/docs/api-reference/chat/create#chat-create-temperature
+    /// [chat completions api]: https://platform.openai.com/docs/api-reference/chat
+    /// [gpt-4o-mini]: https://platform.openai.com/docs/models/gpt-4o-mini
     /// [doc comments]: https://doc.rust-lang.org/rust-by-example/meta/doc.html#doc-comments
     /// [openai api key]: https://help.openai.com/en/articles/4936850-where-do-i-find-my-secret-api-key
-    /// [openai parameters]: https://platform.openai.com/docs/api-reference/completions/create
+    /// [openai parameters]: https://platform.openai.com/docs/api-reference/chat/create
     /// [write a python docstring]: https://platform.openai.com/examples/default-python-docstring
     pub MISSING_DOC_COMMENT_OPENAI,
     Warn,
@@ -139,87 +173,172 @@ impl<'tcx> LateLintPass<'tcx> for MissingDocCommentOpenai {
     }
 
     fn check_item(&mut self, cx: &LateContext<'tcx>, item: &'tcx Item<'tcx>) {
-        let owner_id = item.owner_id;
+        let (noun, required_sections, diag_span) = match item.kind {
+            ItemKind::Fn(fn_sig, _, body_id) => (
+                "function",
+                required_sections(cx, item.owner_id, &fn_sig, Some(body_id)),
+                fn_sig.span,
+            ),
+            ItemKind::Struct(..) => ("struct", Vec::new(), item.ident.span),
+            ItemKind::Enum(..) => ("enum", Vec::new(), item.ident.span),
+            ItemKind::Union(..) => ("union", Vec::new(), item.ident.span),
+            ItemKind::Trait(..) => ("trait", Vec::new(), item.ident.span),
+            ItemKind::TyAlias(..) => ("type alias", Vec::new(), item.ident.span),
+            ItemKind::Const(..) => ("constant", Vec::new(), item.ident.span),
+            ItemKind::Static(..) => ("static", Vec::new(), item.ident.span),
+            _ => return,
+        };
+
+        self.check_doc_target(
+            cx,
+            DocTarget {
+                owner_id: item.owner_id,
+                hir_id: item.hir_id(),
+                item_span: item.span,
+                noun,
+                required_sections,
+                diag_span,
+            },
+        );
+    }
+
+    fn check_trait_item(&mut self, cx: &LateContext<'tcx>, trait_item: &'tcx TraitItem<'tcx>) {
+        let (noun, required_sections, diag_span) = match trait_item.kind {
+            TraitItemKind::Fn(ref fn_sig, ref trait_fn) => {
+                let body_id = match *trait_fn {
+                    TraitFn::Provided(body_id) => Some(body_id),
+                    TraitFn::Required(_) => None,
+                };
+                (
+                    "trait method",
+                    required_sections(cx, trait_item.owner_id, fn_sig, body_id),
+                    fn_sig.span,
+                )
+            }
+            TraitItemKind::Const(..) => ("associated constant", Vec::new(), trait_item.ident.span),
+            TraitItemKind::Type(..) => ("associated type", Vec::new(), trait_item.ident.span),
+        };
+
+        self.check_doc_target(
+            cx,
+            DocTarget {
+                owner_id: trait_item.owner_id,
+                hir_id: trait_item.hir_id(),
+                item_span: trait_item.span,
+                noun,
+                required_sections,
+                diag_span,
+            },
+        );
+    }
+
+    fn check_impl_item(&mut self, cx: &LateContext<'tcx>, impl_item: &'tcx ImplItem<'tcx>) {
+        // smoelius: Skip impl items that implement a trait; their documentation belongs on the
+        // trait definition, not on each implementation.
+        if is_trait_impl_item(cx, impl_item.owner_id) {
+            return;
+        }
+
+        let (noun, required_sections, diag_span) = match impl_item.kind {
+            ImplItemKind::Fn(ref fn_sig, body_id) => (
+                "method",
+                required_sections(cx, impl_item.owner_id, fn_sig, Some(body_id)),
+                fn_sig.span,
+            ),
+            ImplItemKind::Const(..) => ("associated constant", Vec::new(), impl_item.ident.span),
+            ImplItemKind::Type(..) => ("associated type", Vec::new(), impl_item.ident.span),
+        };
+
+        self.check_doc_target(
+            cx,
+            DocTarget {
+                owner_id: impl_item.owner_id,
+                hir_id: impl_item.hir_id(),
+                item_span: impl_item.span,
+                noun,
+                required_sections,
+                diag_span,
+            },
+        );
+    }
+}
+
+/// Everything `check_doc_target` needs to know about the item being considered, regardless of
+/// whether it came from `check_item`, `check_trait_item`, or `check_impl_item`.
+struct DocTarget {
+    owner_id: OwnerId,
+    hir_id: HirId,
+    /// The span of the item's source, passed to OpenAI as the snippet to document.
+    item_span: Span,
+    /// How the item is referred to in prompts and diagnostics, e.g. "struct" or "trait method".
+    noun: &'static str,
+    required_sections: Vec<RequiredSection>,
+    /// Where the "lacks a doc comment" diagnostic points.
+    diag_span: Span,
+}
 
+impl MissingDocCommentOpenai {
+    fn check_doc_target(&mut self, cx: &LateContext<'_>, target: DocTarget) {
         // smoelius: The next two checks were copied from:
         // https://github.com/rust-lang/rust-clippy/blob/92c4f1e2d9db43ebc0449fbbc2150eeb9429e65b/clippy_lints/src/doc.rs#L372-L384
 
-        if !cx.effective_visibilities.is_exported(owner_id.def_id) {
-            return; // Private functions do not require doc comments
+        if !cx
+            .effective_visibilities
+            .is_exported(target.owner_id.def_id)
+        {
+            return; // Private items do not require doc comments
         }
 
         // do not lint if any parent has `#[doc(hidden)]` attribute (#7347)
         if cx
             .tcx
             .hir()
-            .parent_iter(owner_id.into())
+            .parent_iter(target.owner_id.into())
             .any(|(id, _node)| is_doc_hidden(cx.tcx.hir().attrs(id)))
         {
             return;
         }
 
-        // smoelius: Only enable for functions for now.
-        let ItemKind::Fn(
-            FnSig {
-                span: fn_sig_span, ..
-            },
-            _,
-            _,
-        ) = item.kind
-        else {
-            return;
-        };
-
         if cx
             .tcx
             .hir()
-            .attrs(item.hir_id())
+            .attrs(target.hir_id)
             .iter()
-            .any(|attr| matches!(attr.kind, AttrKind::DocComment{ .. }))
+            .any(|attr| matches!(attr.kind, AttrKind::DocComment { .. }))
         {
             return;
         }
 
         let doc_comment = std::env::var(OPENAI_API_KEY).ok().and_then(|api_key| {
-            let snippet = snippet_opt(cx, item.span)?;
-
-            let request = self.request_from_snippet(&snippet);
-
-            let response = match send_request(&api_key, &request) {
-                Ok(response) => response,
-                Err(error) => {
-                    cx.sess().dcx().span_warn(fn_sig_span, error.to_string());
-                    return None;
-                }
-            };
-
-            response
-                .choices
-                .first()
-                .and_then(|choice| extract_doc_comment(&choice.text))
-                .or_else(|| {
-                    cx.sess().dcx().span_warn(
-                        fn_sig_span,
-                        format!("Could not extract doc comment from response: {response:#?}",),
-                    );
-                    None
-                })
+            let snippet = snippet_opt(cx, target.item_span)?;
+
+            self.doc_comment_from_snippet(
+                cx,
+                &api_key,
+                &snippet,
+                &target.required_sections,
+                target.noun,
+                target.diag_span,
+            )
         });
 
-        let insertion_point = skip_preceding_line_comments(cx, earliest_attr_span(cx, item));
+        let insertion_point = skip_preceding_line_comments(
+            cx,
+            earliest_attr_span(cx, target.hir_id, target.item_span),
+        );
 
         span_lint_and_then(
             cx,
             MISSING_DOC_COMMENT_OPENAI,
-            fn_sig_span,
-            "exported function lacks a doc comment",
+            target.diag_span,
+            format!("exported {} lacks a doc comment", target.noun),
             |diag| {
-                if let Some(doc_comment) = doc_comment {
+                if let Some((doc_comment, applicability)) = doc_comment {
                     diag.span_suggestion(
                         insertion_point.with_hi(insertion_point.lo()),
                         "use the following suggestion from OpenAI",
                         doc_comment,
-                        rustc_errors::Applicability::MachineApplicable,
+                        applicability,
                     );
                 }
             },
@@ -227,16 +346,104 @@ impl<'tcx> LateLintPass<'tcx> for MissingDocCommentOpenai {
     }
 }
 
+fn is_trait_impl_item(cx: &LateContext<'_>, owner_id: OwnerId) -> bool {
+    let parent_id = cx.tcx.hir().get_parent_item(owner_id.into());
+    matches!(
+        cx.tcx.hir().expect_item(parent_id.def_id).kind,
+        ItemKind::Impl(impl_) if impl_.of_trait.is_some()
+    )
+}
+
 impl MissingDocCommentOpenai {
-    fn request_from_snippet(&self, snippet: &str) -> openai::Request {
+    // smoelius: Ask OpenAI again, but only about the sections the first response was missing.
+    // This gives the model a second, more targeted chance before we give up and warn.
+    fn doc_comment_from_snippet(
+        &self,
+        cx: &LateContext<'_>,
+        api_key: &str,
+        snippet: &str,
+        required_sections: &[RequiredSection],
+        noun: &str,
+        diag_span: Span,
+    ) -> Option<(String, Applicability)> {
+        let request = self.request_from_snippet(snippet, required_sections, noun);
+
+        let response = match send_request(api_key, &request) {
+            Ok(response) => response,
+            Err(error) => {
+                cx.sess().dcx().span_warn(diag_span, error.to_string());
+                return None;
+            }
+        };
+
+        let mut comment = response
+            .choices
+            .first()
+            .and_then(|choice| extract_doc_comment(&choice.message.content))
+            .or_else(|| {
+                cx.sess().dcx().span_warn(
+                    diag_span,
+                    format!("Could not extract doc comment from response: {response:#?}",),
+                );
+                None
+            })?;
+
+        let missing = missing_sections(&comment, required_sections);
+        if !missing.is_empty() {
+            let retry_request = self.request_from_snippet(snippet, &missing, noun);
+            if let Ok(retry_response) = send_request(api_key, &retry_request) {
+                if let Some(retry_comment) = retry_response
+                    .choices
+                    .first()
+                    .and_then(|choice| extract_doc_comment(&choice.message.content))
+                {
+                    comment = retry_comment;
+                }
+            }
+
+            let still_missing = missing_sections(&comment, required_sections);
+            if !still_missing.is_empty() {
+                let headings = still_missing
+                    .iter()
+                    .map(|section| section.heading())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                cx.sess().dcx().span_warn(
+                    diag_span,
+                    format!("generated doc comment is missing required section(s): {headings}"),
+                );
+            }
+        }
+
+        match markdown::validate(&comment) {
+            markdown::Validation::Ok(rewritten) => {
+                Some((rewritten, Applicability::MachineApplicable))
+            }
+            markdown::Validation::Questionable => Some((comment, Applicability::MaybeIncorrect)),
+            markdown::Validation::Invalid => {
+                cx.sess().dcx().span_warn(
+                    diag_span,
+                    "generated doc comment has unbalanced code fences; discarding suggestion",
+                );
+                None
+            }
+        }
+    }
+
+    fn request_from_snippet(
+        &self,
+        snippet: &str,
+        required_sections: &[RequiredSection],
+        noun: &str,
+    ) -> openai::Request<'static> {
         openai::Request {
-            prompt: self.prompt_from_snippet(snippet),
             model: self
                 .config
                 .model
                 .as_deref()
                 .unwrap_or(DEFAULT_MODEL)
                 .to_owned(),
+            messages: self.messages_from_snippet(snippet, required_sections, noun),
             max_tokens: self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
             temperature: self.config.temperature.unwrap_or(DEFAULT_TEMPERATURE),
             top_p: self.config.top_p,
@@ -246,23 +453,187 @@ impl MissingDocCommentOpenai {
         }
     }
 
-    fn prompt_from_snippet(&self, snippet: &str) -> String {
-        format!(
-            "```rust\n{snippet}\n```\n{}\n```rust\n",
-            self.config.prompt.as_deref().unwrap_or(DEFAULT_PROMPT)
-        )
+    fn messages_from_snippet(
+        &self,
+        snippet: &str,
+        required_sections: &[RequiredSection],
+        noun: &str,
+    ) -> Vec<openai::Message> {
+        let mut system = format!("{SYSTEM_PREAMBLE} {}", self.prompt_for(noun));
+        for section in required_sections {
+            let _ = write!(system, " Include {}.", section.description());
+        }
+
+        vec![
+            openai::Message {
+                role: "system".to_owned(),
+                content: system,
+            },
+            openai::Message {
+                role: "user".to_owned(),
+                content: format!("```rust\n{snippet}\n```"),
+            },
+        ]
+    }
+
+    // smoelius: A custom `prompt` is used verbatim, regardless of the item's kind, since we have
+    // no way to know how the user phrased it. The default is reworded per kind (e.g., "struct"
+    // instead of "function") to match Clippy's wording for its "above <kind>" doc suggestions.
+    fn prompt_for(&self, noun: &str) -> String {
+        self.config.prompt.clone().unwrap_or_else(|| {
+            format!("An elaborate, high quality rustdoc comment for the above {noun}:")
+        })
+    }
+}
+
+/// A Markdown section that Clippy-style doc lints expect to see for certain kinds of functions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RequiredSection {
+    Safety,
+    Errors,
+    Panics,
+}
+
+impl RequiredSection {
+    fn heading(self) -> &'static str {
+        match self {
+            Self::Safety => "# Safety",
+            Self::Errors => "# Errors",
+            Self::Panics => "# Panics",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Safety => {
+                "a \"# Safety\" section explaining the safety invariants the caller must uphold"
+            }
+            Self::Errors => {
+                "an \"# Errors\" section describing the conditions under which the function \
+                 returns an `Err`"
+            }
+            Self::Panics => {
+                "a \"# Panics\" section describing the conditions under which the function panics"
+            }
+        }
+    }
+}
+
+// smoelius: The following three checks mirror the cases Clippy's `doc.rs` treats as requiring a
+// dedicated section: `missing_safety_doc`, `missing_errors_doc`, and `missing_panics_doc`.
+fn required_sections<'tcx>(
+    cx: &LateContext<'tcx>,
+    owner_id: OwnerId,
+    fn_sig: &FnSig<'tcx>,
+    body_id: Option<BodyId>,
+) -> Vec<RequiredSection> {
+    let mut sections = Vec::new();
+
+    if fn_sig.header.safety.is_unsafe() {
+        sections.push(RequiredSection::Safety);
+    }
+
+    if returns_result(cx, owner_id) {
+        sections.push(RequiredSection::Errors);
+    }
+
+    // smoelius: A required trait method has no body to inspect, so we cannot tell whether (the
+    // eventual) implementations will panic.
+    if body_id.is_some_and(|body_id| body_can_panic(cx, body_id)) {
+        sections.push(RequiredSection::Panics);
+    }
+
+    sections
+}
+
+fn returns_result<'tcx>(cx: &LateContext<'tcx>, owner_id: OwnerId) -> bool {
+    let sig = cx.tcx.fn_sig(owner_id.to_def_id()).instantiate_identity();
+    is_type_diagnostic_item(cx, sig.skip_binder().output(), sym::Result)
+}
+
+fn body_can_panic(cx: &LateContext<'_>, body_id: BodyId) -> bool {
+    let body = cx.tcx.hir().body(body_id);
+    let mut finder = PanicFinder { cx, found: false };
+    finder.visit_expr(body.value);
+    finder.found
+}
+
+// smoelius: An early-exit-on-first-match visitor using `intravisit`'s default recursion.
+struct PanicFinder<'a, 'tcx> {
+    cx: &'a LateContext<'tcx>,
+    found: bool,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for PanicFinder<'a, 'tcx> {
+    type NestedFilter = nested_filter::OnlyBodies;
+
+    fn nested_visit_map(&mut self) -> Self::NestedFilter {
+        nested_filter::OnlyBodies
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        if self.found {
+            return;
+        }
+
+        if expr_can_panic(self.cx, expr) {
+            self.found = true;
+            return;
+        }
+
+        intravisit::walk_expr(self, expr);
     }
 }
 
-fn send_request(api_key: &str, request: &openai::Request) -> Result<openai::Response, IoError> {
+const PANIC_LIKE_MACROS: &[&str] = &[
+    "panic",
+    "assert",
+    "assert_eq",
+    "assert_ne",
+    "debug_assert",
+    "debug_assert_eq",
+    "debug_assert_ne",
+    "unreachable",
+    "todo",
+    "unimplemented",
+];
+
+const PANIC_LIKE_METHODS: &[&str] = &["unwrap", "unwrap_err", "expect", "expect_err"];
+
+fn expr_can_panic(cx: &LateContext<'_>, expr: &Expr<'_>) -> bool {
+    match expr.kind {
+        ExprKind::Index(..) => true,
+        ExprKind::MethodCall(path, ..) => PANIC_LIKE_METHODS.contains(&path.ident.name.as_str()),
+        _ => root_macro_call_first_node(cx, expr).is_some_and(|macro_call| {
+            PANIC_LIKE_MACROS.contains(&cx.tcx.item_name(macro_call.def_id).as_str())
+        }),
+    }
+}
+
+fn missing_sections(comment: &str, required_sections: &[RequiredSection]) -> Vec<RequiredSection> {
+    required_sections
+        .iter()
+        .copied()
+        .filter(|section| !comment_has_section(comment, *section))
+        .collect()
+}
+
+fn comment_has_section(comment: &str, section: RequiredSection) -> bool {
+    comment
+        .lines()
+        .any(|line| line.trim_start_matches("///").trim() == section.heading())
+}
+
+fn send_request(api_key: &str, request: &openai::Request<'_>) -> Result<openai::Response, IoError> {
     if testing() {
         return Ok(openai::Response {
             choices: vec![openai::Choice {
-                text: MOCK_COMPLETION.to_owned(),
+                message: openai::ResponseMessage {
+                    content: MOCK_COMPLETION.to_owned(),
+                },
                 index: 0,
                 finish_reason: "stop".to_owned(),
             }],
-            ..Default::default()
         });
     }
 
@@ -325,10 +696,9 @@ fn send(api_key: &str, mut data: &[u8]) -> Result<(u32, Vec<u8>), IoError> {
 }
 
 fn extract_doc_comment(response: &str) -> Option<String> {
-    // smoelius: Sanity. According to:
-    // https://platform.openai.com/docs/api-reference/completions/create#completions/create-stop
-    //
-    //   The returned text will not contain the stop sequence.
+    // smoelius: Sanity. Per the `stop` parameter's documentation, the returned text will not
+    // contain the stop sequence:
+    // https://platform.openai.com/docs/api-reference/chat/create#chat-create-stop
     assert_ne!(response.lines().last(), Some(STOP));
 
     // smoelius: In several of my experiments, the last several lines of the response did not start
@@ -353,15 +723,21 @@ fn extract_doc_comment(response: &str) -> Option<String> {
     }
 }
 
-fn earliest_attr_span(cx: &LateContext<'_>, item: &Item<'_>) -> Span {
+fn earliest_attr_span(cx: &LateContext<'_>, hir_id: HirId, item_span: Span) -> Span {
     cx.tcx
         .hir()
-        .attrs(item.hir_id())
+        .attrs(hir_id)
         .iter()
         .map(|attr| attr.span)
         .fold(
-            item.span,
-            |lhs, rhs| if lhs.lo() <= rhs.lo() { lhs } else { rhs },
+            item_span,
+            |lhs, rhs| {
+                if lhs.lo() <= rhs.lo() {
+                    lhs
+                } else {
+                    rhs
+                }
+            },
         )
 }
 
@@ -0,0 +1,288 @@
+//! Validates a generated doc comment as Markdown/rustdoc before it becomes a suggestion.
+//!
+//! Loosely mirrors the doctest checks in Clippy's `doc.rs`:
+//! <https://github.com/rust-lang/rust-clippy/blob/master/clippy_lints/src/doc.rs>. Every fenced
+//! code block must have a matching closer, and every ```rust example must still parse once a
+//! needless `fn main() {}` wrapper (`clippy::needless_doctest_main`) is stripped.
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use rustc_parse::parser::ForceCollect;
+use rustc_session::parse::ParseSess;
+use rustc_span::FileName;
+
+/// The outcome of validating a candidate doc comment.
+pub(crate) enum Validation {
+    /// Safe to insert. If a fenced example had a needless `fn main` wrapper, it was stripped.
+    Ok(String),
+    /// Still worth offering, but a fenced ```rust example did not parse as Rust, so the
+    /// suggestion should be downgraded (e.g., to `Applicability::MaybeIncorrect`).
+    Questionable,
+    /// The comment's code fences are unbalanced; it should not be suggested at all.
+    Invalid,
+}
+
+/// Validates `comment`, a candidate doc comment whose lines all begin with `///`.
+pub(crate) fn validate(comment: &str) -> Validation {
+    if !fences_balanced(comment) {
+        return Validation::Invalid;
+    }
+
+    let markdown = strip_doc_comment_markers(comment);
+    let rust_examples = rust_code_block_spans(&markdown);
+
+    let mut rewritten = markdown.clone();
+    let mut offset: isize = 0;
+    let mut questionable = false;
+
+    for (start, end) in rust_examples {
+        let example = &markdown[start..end];
+
+        // smoelius: Try stripping a `fn main() {}` wrapper first. A well-formed `fn main() {}`
+        // example already parses as-is (it's a valid item), so checking `parses_as_rust` before
+        // this would always take the example verbatim and never unwrap it.
+        if let Some(inner) = strip_fn_main_wrapper(example).filter(|inner| parses_as_rust(inner)) {
+            // Keep the block's trailing newline so the closing fence stays on its own line.
+            let inner = if example.ends_with('\n') { inner + "\n" } else { inner };
+            let lo = (start as isize + offset) as usize;
+            let hi = (end as isize + offset) as usize;
+            offset += inner.len() as isize - (end - start) as isize;
+            rewritten.replace_range(lo..hi, &inner);
+            continue;
+        }
+
+        if !parses_as_rust(example) {
+            questionable = true;
+        }
+    }
+
+    if questionable {
+        Validation::Questionable
+    } else {
+        Validation::Ok(reindent_as_doc_comment(&rewritten))
+    }
+}
+
+fn fences_balanced(comment: &str) -> bool {
+    comment
+        .lines()
+        .filter(|line| {
+            line.trim_start_matches("///")
+                .trim_start()
+                .starts_with("```")
+        })
+        .count()
+        % 2
+        == 0
+}
+
+fn strip_doc_comment_markers(comment: &str) -> String {
+    comment
+        .lines()
+        .map(|line| {
+            let line = line.strip_prefix("///").unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn reindent_as_doc_comment(markdown: &str) -> String {
+    let mut comment = String::new();
+    for line in markdown.lines() {
+        if line.is_empty() {
+            comment.push_str("///\n");
+        } else {
+            comment.push_str("/// ");
+            comment.push_str(line);
+            comment.push('\n');
+        }
+    }
+    comment
+}
+
+/// Returns the byte ranges (into `markdown`) of every fenced code block whose language is Rust
+/// (including an unspecified language, per rustdoc's default).
+fn rust_code_block_spans(markdown: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut current: Option<(bool, usize, usize)> = None;
+
+    for (event, range) in Parser::new_ext(markdown, Options::empty()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                current = Some((is_rust_lang(&lang), usize::MAX, 0));
+            }
+            Event::Text(_) => {
+                if let Some((_, start, end)) = current.as_mut() {
+                    *start = (*start).min(range.start);
+                    *end = (*end).max(range.end);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((true, start, end)) = current.take() {
+                    if start <= end {
+                        spans.push((start, end));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    spans
+}
+
+fn is_rust_lang(lang: &str) -> bool {
+    match lang.split(',').next() {
+        Some(lang) => lang.is_empty() || matches!(lang, "rust" | "rs"),
+        None => true,
+    }
+}
+
+/// Returns whether `code` parses as Rust the way rustdoc treats a fenced example: as a sequence
+/// of statements inside an implicit `fn` body, not as bare top-level items. A doctest like
+/// `let x = 5;\nassert_eq!(x, 5);` is not valid at the top level of a file, but it is exactly
+/// what rustdoc (and `cargo test --doc`) compiles by wrapping it in `fn main() { .. }`.
+fn parses_as_rust(code: &str) -> bool {
+    parses_as_item(&format!("fn __dylint_doctest() {{\n{code}\n}}"))
+}
+
+/// Returns whether `code` parses as a sequence of top-level items. [`parses_as_rust`] is built on
+/// this by wrapping the example in a synthetic `fn`, so that a doctest body of items, statements,
+/// or both is accepted the same way rustdoc accepts it.
+fn parses_as_item(code: &str) -> bool {
+    let psess = ParseSess::with_silent_emitter(None);
+    let mut parser = match rustc_parse::new_parser_from_source_str(
+        &psess,
+        FileName::Custom("doctest".to_owned()),
+        code.to_owned(),
+    ) {
+        Ok(parser) => parser,
+        Err(errs) => {
+            errs.into_iter().for_each(|mut err| err.cancel());
+            return false;
+        }
+    };
+
+    loop {
+        match parser.parse_item(ForceCollect::No) {
+            Ok(Some(_)) => {}
+            // The parser recovers from errors inside a block by emitting them and carrying on, so
+            // reaching the end is not enough; no error may have been emitted along the way.
+            Ok(None) => return psess.dcx().has_errors().is_none(),
+            Err(mut err) => {
+                err.cancel();
+                return false;
+            }
+        }
+    }
+}
+
+/// If `example` is exactly a `fn main() { .. }` wrapper (the shape `needless_doctest_main`
+/// flags), returns its dedented body.
+fn strip_fn_main_wrapper(example: &str) -> Option<String> {
+    let trimmed = example.trim();
+    let inner = trimmed
+        .strip_prefix("fn main()")?
+        .trim_start()
+        .strip_prefix('{')?
+        .strip_suffix('}')?;
+
+    if braces_balanced(inner) {
+        Some(dedent(inner))
+    } else {
+        None
+    }
+}
+
+fn braces_balanced(s: &str) -> bool {
+    let mut depth = 0i32;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+fn dedent(s: &str) -> String {
+    let indent = s
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    s.lines()
+        .map(|line| line.get(indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fences_balanced_accepts_matched_fences() {
+        assert!(fences_balanced("/// ```\n/// let x = 5;\n/// ```\n"));
+    }
+
+    #[test]
+    fn fences_balanced_rejects_unmatched_fence() {
+        assert!(!fences_balanced("/// ```\n/// let x = 5;\n"));
+    }
+
+    #[test]
+    fn strip_fn_main_wrapper_dedents_the_body() {
+        let wrapper = "fn main() {\n    let x = 5;\n    assert_eq!(x, 5);\n}";
+        assert_eq!(
+            strip_fn_main_wrapper(wrapper).as_deref(),
+            Some("let x = 5;\nassert_eq!(x, 5);")
+        );
+    }
+
+    #[test]
+    fn strip_fn_main_wrapper_rejects_non_wrapper() {
+        assert_eq!(strip_fn_main_wrapper("let x = 5;"), None);
+    }
+
+    #[test]
+    fn parses_as_rust_accepts_plain_statements() {
+        // smoelius: This is the shape of an ordinary "# Examples" block; rustdoc compiles it by
+        // implicitly wrapping it in `fn main() { .. }`, so our check must do the same.
+        assert!(parses_as_rust("let x = 5;\nassert_eq!(x, 5);"));
+    }
+
+    #[test]
+    fn parses_as_rust_rejects_garbage() {
+        assert!(!parses_as_rust("let x = ;"));
+    }
+
+    #[test]
+    fn validate_accepts_a_plain_statement_example() {
+        let comment =
+            "/// # Examples\n///\n/// ```\n/// let x = 5;\n/// assert_eq!(x, 5);\n/// ```\n";
+        assert!(matches!(validate(comment), Validation::Ok(_)));
+    }
+
+    #[test]
+    fn validate_rejects_unbalanced_fences() {
+        let comment = "/// ```\n/// let x = 5;\n";
+        assert!(matches!(validate(comment), Validation::Invalid));
+    }
+
+    #[test]
+    fn validate_downgrades_unparseable_example() {
+        let comment = "/// ```\n/// let x = ;\n/// ```\n";
+        assert!(matches!(validate(comment), Validation::Questionable));
+    }
+}
@@ -0,0 +1,45 @@
+//! Request/response types for OpenAI's [Chat Completions API].
+//!
+//! [Chat Completions API]: https://platform.openai.com/docs/api-reference/chat
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct Request<'a> {
+    pub model: String,
+    pub messages: Vec<Message>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    pub stop: &'a [&'a str],
+}
+
+#[derive(Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Response {
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Choice {
+    pub message: ResponseMessage,
+    pub index: u32,
+    pub finish_reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResponseMessage {
+    #[serde(default)]
+    pub content: String,
+}
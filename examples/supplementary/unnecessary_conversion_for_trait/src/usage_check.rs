@@ -1,80 +1,249 @@
-use rustc_hir::{Expr, HirId, intravisit::{self, Visitor}};
+use rustc_hir::{def::Res, ExprKind, HirId, Node, QPath};
 use rustc_lint::LateContext;
-use rustc_middle::hir::nested_filter;
+use rustc_middle::mir::{self, Local, Location, TerminatorKind, VarDebugInfoContents};
+use rustc_mir_dataflow::{impls::MaybeLiveLocals, Analysis};
+use rustc_span::Span;
 
-/// Visitor to check if a HirId is used in code
-pub(crate) struct UsageVisitor {
-    hir_id: HirId,
-    found: bool,
-}
+/// Checks if the given HirId is used later in the code after the specified span.
+///
+/// This used to be a HIR walk comparing `Span` ordering (`stmt.span > call_span`), but span order
+/// doesn't track control flow: it misses uses inside later loops, match arms, and closures, and
+/// can be fooled by macro-expanded spans (see `ui/false_positive_iter.rs`). Instead, this resolves
+/// `hir_id` to its MIR `Local` and asks a backward `MaybeLiveLocals` dataflow pass whether that
+/// `Local` is live just after `call_span`.
+pub(crate) fn is_used_later<'tcx>(cx: &LateContext<'tcx>, hir_id: HirId, call_span: Span) -> bool {
+    let body_id = cx.tcx.hir().enclosing_body_owner(hir_id);
+    let def_id = cx.tcx.hir().body_owner_def_id(body_id).to_def_id();
+
+    if !cx.tcx.is_mir_available(def_id) {
+        // smoelius: No MIR to analyze. Assume the value is used, so we never suggest removing a
+        // conversion that might still be needed.
+        return true;
+    }
+
+    let body = cx.tcx.optimized_mir(def_id);
+
+    let Some(local) = resolve_local(cx, body, hir_id) else {
+        // smoelius: Couldn't map the HIR node to a MIR `Local`. Fall back to the conservative
+        // answer so the lint stays sound.
+        return true;
+    };
 
-impl<'tcx> Visitor<'tcx> for UsageVisitor {
-    type NestedFilter = nested_filter::OnlyBodies;
+    let Some(location) = find_call_location(body, call_span) else {
+        return true;
+    };
 
-    fn nested_visit_map(&mut self) -> Self::NestedFilter {
-        nested_filter::OnlyBodies
+    if !is_live_after(cx.tcx, body, local, location) {
+        return false;
     }
 
-    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
-        // Early return if we've already found what we're looking for
-        if self.found {
-            return;
+    // smoelius: The local is live after the call, but it might be live only because of a
+    // scope-ending `Drop`, which isn't a real use. Walk forward from `location` and check whether
+    // every subsequent mention of `local` is such a `Drop`.
+    !only_reachable_via_drop(body, local, location)
+}
+
+/// Resolves `hir_id` (the receiver, e.g. `xs` in `xs.iter()`) to the `Local` its binding was
+/// lowered to, by matching the binding's `Span` against the body's `var_debug_info`. This
+/// deliberately does *not* resolve to the temporary `.iter()` creates.
+fn resolve_local(cx: &LateContext<'_>, body: &mir::Body<'_>, hir_id: HirId) -> Option<Local> {
+    let Node::Expr(expr) = cx.tcx.hir_node(hir_id) else {
+        return None;
+    };
+    let ExprKind::Path(QPath::Resolved(None, path)) = expr.kind else {
+        return None;
+    };
+    let Res::Local(binding_hir_id) = path.res else {
+        return None;
+    };
+
+    let binding_span = cx.tcx.hir().span(binding_hir_id);
+
+    body.var_debug_info.iter().find_map(|info| {
+        if info.source_info.span != binding_span {
+            return None;
+        }
+        match info.value {
+            VarDebugInfoContents::Place(place) => place.as_local(),
+            VarDebugInfoContents::Const(_) => None,
         }
-        
-        // Check if this is the HirId we're looking for
-        if expr.hir_id == self.hir_id {
-            self.found = true;
-            return;
+    })
+}
+
+/// Finds the MIR `Location` of the statement or terminator whose source span is exactly
+/// `call_span`.
+fn find_call_location(body: &mir::Body<'_>, call_span: Span) -> Option<Location> {
+    for (block, data) in body.basic_blocks.iter_enumerated() {
+        for (statement_index, stmt) in data.statements.iter().enumerate() {
+            if stmt.source_info.span == call_span {
+                return Some(Location {
+                    block,
+                    statement_index,
+                });
+            }
+        }
+        if let Some(terminator) = &data.terminator {
+            if terminator.source_info.span == call_span {
+                return Some(Location {
+                    block,
+                    statement_index: data.statements.len(),
+                });
+            }
         }
-        
-        // Let intravisit handle the recursion uniformly for all expression types
-        intravisit::walk_expr(self, expr);
     }
+    None
 }
 
-/// Helper function that checks whether a given node contains the HirId usage
-fn usage_found_in<'tcx, T>(
-    hir_id: HirId,
-    node: &'tcx T,
-    visit_fn: impl FnOnce(&mut UsageVisitor, &'tcx T),
+fn is_live_after(
+    tcx: rustc_middle::ty::TyCtxt<'_>,
+    body: &mir::Body<'_>,
+    local: Local,
+    location: Location,
 ) -> bool {
-    let mut visitor = UsageVisitor {
-        hir_id,
-        found: false,
-    };
-    visit_fn(&mut visitor, node);
-    visitor.found
+    let mut cursor = MaybeLiveLocals
+        .into_engine(tcx, body)
+        .iterate_to_fixpoint()
+        .into_results_cursor(body);
+
+    // smoelius: `MaybeLiveLocals` is a backward analysis, so the state before `location`'s primary
+    // effect is the state just after `location` in program order.
+    cursor.seek_before_primary_effect(location);
+    cursor.get().contains(local)
 }
 
-/// Checks if the given HirId is used later in the code after the specified span
-pub(crate) fn is_used_later<'tcx>(
-    cx: &LateContext<'tcx>,
-    hir_id: HirId,
-    call_span: rustc_span::Span,
-) -> bool {
-    let body_id = cx.tcx.hir().enclosing_body_owner(hir_id);
-    let body = cx.tcx.hir().body(body_id).unwrap();
-    let mut visitor = UsageVisitor { hir_id, found: false };
-
-    // Traverse statements after call_span
-    for stmt in &body.value.stmts {
-        if stmt.span > call_span {
-            visitor.visit_stmt(stmt);
-            if visitor.found {
-                return true;
+/// Returns `true` if at least one `Drop` of `local` is reachable from `location` and every
+/// mention of `local` reachable from `location` (other than `location` itself) is a `Drop`
+/// terminator, i.e., the local is never put to a real use again. If no mention is found at all,
+/// returns `false`.
+fn only_reachable_via_drop(body: &mir::Body<'_>, local: Local, location: Location) -> bool {
+    let mut visited = vec![false; body.basic_blocks.len()];
+
+    // smoelius: Start from what comes *after* `location`, not `location` itself. `location` is
+    // the conversion call being analyzed, and a by-value conversion (e.g. `Into::into(xs)`,
+    // `xs.into_iter()`) moves `local` into that very call's `args`, which is not a later use.
+    //
+    // `find_call_location` is expected to always land on the block's terminator, since a call
+    // expression lowers to a `Call` terminator, never a statement. But rather than assume that
+    // and risk silently skipping statements between `location` and the terminator if it's ever
+    // wrong, scan them explicitly; this is a no-op in the expected case, since there are none.
+    let data = &body.basic_blocks[location.block];
+    let after_location = (location.statement_index + 1).min(data.statements.len());
+    if data.statements[after_location..]
+        .iter()
+        .any(|stmt| mentions_local_stmt(&stmt.kind, local))
+    {
+        return false;
+    }
+
+    let mut worklist: Vec<Location> = match &data.terminator {
+        Some(terminator) => terminator
+            .successors()
+            .map(|block| Location {
+                block,
+                statement_index: 0,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for loc in &worklist {
+        visited[loc.block.index()] = true;
+    }
+
+    let mut any_mention = false;
+
+    while let Some(loc) = worklist.pop() {
+        let data = &body.basic_blocks[loc.block];
+
+        if data.statements[loc.statement_index..]
+            .iter()
+            .any(|stmt| mentions_local_stmt(&stmt.kind, local))
+        {
+            return false;
+        }
+
+        let Some(terminator) = &data.terminator else {
+            continue;
+        };
+
+        match &terminator.kind {
+            TerminatorKind::Drop { place, target, .. } if place.as_local() == Some(local) => {
+                any_mention = true;
+                if let Some(slot) = visited.get_mut(target.index()) {
+                    if !*slot {
+                        *slot = true;
+                        worklist.push(Location {
+                            block: *target,
+                            statement_index: 0,
+                        });
+                    }
+                }
+            }
+            kind => {
+                if mentions_local_term(kind, local) {
+                    return false;
+                }
+                for successor in terminator.successors() {
+                    if let Some(slot) = visited.get_mut(successor.index()) {
+                        if !*slot {
+                            *slot = true;
+                            worklist.push(Location {
+                                block: successor,
+                                statement_index: 0,
+                            });
+                        }
+                    }
+                }
             }
         }
     }
 
-    // Traverse the return expression if available
-    if let Some(expr) = body.value.expr {
-        if expr.span > call_span {
-            visitor.visit_expr(expr);
-            if visitor.found {
-                return true;
-            }
+    any_mention
+}
+
+fn mentions_local_stmt(kind: &mir::StatementKind<'_>, local: Local) -> bool {
+    match kind {
+        mir::StatementKind::Assign(box (place, rvalue)) => {
+            place.as_local() == Some(local) || mentions_local_rvalue(rvalue, local)
         }
+        _ => false,
     }
+}
 
-    false
-} 
\ No newline at end of file
+fn mentions_local_term(kind: &TerminatorKind<'_>, local: Local) -> bool {
+    match kind {
+        TerminatorKind::Call { func, args, .. } => {
+            mentions_local_operand(func, local)
+                || args
+                    .iter()
+                    .any(|arg| mentions_local_operand(&arg.node, local))
+        }
+        TerminatorKind::Assert { cond, .. } => mentions_local_operand(cond, local),
+        TerminatorKind::Drop { place, .. } => place.as_local() == Some(local),
+        _ => false,
+    }
+}
+
+fn mentions_local_rvalue(rvalue: &mir::Rvalue<'_>, local: Local) -> bool {
+    match rvalue {
+        mir::Rvalue::Use(operand) | mir::Rvalue::Cast(_, operand, _) => {
+            mentions_local_operand(operand, local)
+        }
+        mir::Rvalue::Ref(_, _, place) | mir::Rvalue::CopyForDeref(place) => {
+            place.as_local() == Some(local)
+        }
+        mir::Rvalue::BinaryOp(_, box (lhs, rhs)) => {
+            mentions_local_operand(lhs, local) || mentions_local_operand(rhs, local)
+        }
+        mir::Rvalue::Aggregate(_, fields) => fields
+            .iter()
+            .any(|field| mentions_local_operand(field, local)),
+        _ => false,
+    }
+}
+
+fn mentions_local_operand(operand: &mir::Operand<'_>, local: Local) -> bool {
+    match operand {
+        mir::Operand::Copy(place) | mir::Operand::Move(place) => place.as_local() == Some(local),
+        mir::Operand::Constant(_) => false,
+    }
+}